@@ -1,20 +1,105 @@
 use wasm_bindgen::prelude::*;
-use image::{ImageBuffer, Rgba, ImageFormat};
+use image::{ImageBuffer, Rgba, Rgb, ImageFormat, ImageDecoder};
 use base64::{Engine as _, engine::general_purpose};
 
+/// Output encoding requested for a composited image. JPEG has no alpha channel, so
+/// it is always flattened against a background color before encoding.
+enum OutputFormat {
+    Png,
+    Jpeg { quality: u8 },
+    // `quality` is accepted but currently unused: the pure-Rust WebP encoder in the
+    // `image` crate only supports lossless output, so this degrades to `WebPLossless`.
+    #[allow(dead_code)]
+    WebP { quality: u8 },
+    WebPLossless,
+}
+
+fn parse_output_format(format: &str, quality: u8) -> Result<OutputFormat, JsValue> {
+    match format {
+        "png" => Ok(OutputFormat::Png),
+        "jpeg" | "jpg" => Ok(OutputFormat::Jpeg { quality }),
+        "webp" => Ok(OutputFormat::WebP { quality }),
+        "webp-lossless" => Ok(OutputFormat::WebPLossless),
+        other => Err(JsValue::from_str(&format!("Unknown output format: {}", other))),
+    }
+}
+
+// Reads an RGB background color from a 3-byte slice, defaulting to white when absent.
+// Only used by formats without an alpha channel (currently JPEG).
+fn parse_background(background_color: &[u8]) -> Rgb<u8> {
+    match background_color {
+        [r, g, b] => Rgb([*r, *g, *b]),
+        _ => Rgb([255, 255, 255]),
+    }
+}
+
+// Flattens an RGBA image onto a solid background, for codecs like JPEG that have no
+// alpha channel.
+fn flatten_alpha(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, background: Rgb<u8>) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        let pixel = img.get_pixel(x, y);
+        let alpha = pixel[3] as f32 / 255.0;
+        let blend_channel = |channel: usize| -> u8 {
+            let fg = pixel[channel] as f32;
+            let bg = background[channel] as f32;
+            (fg * alpha + bg * (1.0 - alpha)) as u8
+        };
+        Rgb([blend_channel(0), blend_channel(1), blend_channel(2)])
+    })
+}
+
+// Encodes `img` per `format`, returning the encoded bytes and the MIME subtype used
+// for the `data:image/<mime>;base64,` prefix.
+fn encode_output(
+    img: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    format: OutputFormat,
+    background: Rgb<u8>,
+) -> Result<(Vec<u8>, &'static str), JsValue> {
+    let mut buffer = Vec::new();
+    match format {
+        OutputFormat::Png => {
+            img.write_to(&mut std::io::Cursor::new(&mut buffer), ImageFormat::Png)
+                .map_err(|e| JsValue::from_str(&format!("Failed to encode PNG: {}", e)))?;
+            Ok((buffer, "png"))
+        }
+        OutputFormat::Jpeg { quality } => {
+            let flattened = flatten_alpha(img, background);
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, quality);
+            flattened
+                .write_with_encoder(encoder)
+                .map_err(|e| JsValue::from_str(&format!("Failed to encode JPEG: {}", e)))?;
+            Ok((buffer, "jpeg"))
+        }
+        // The pure-Rust WebP encoder shipped with the `image` crate only supports
+        // lossless output, so a lossy `quality` request currently degrades to lossless.
+        OutputFormat::WebP { quality: _ } | OutputFormat::WebPLossless => {
+            let encoder = image::codecs::webp::WebPEncoder::new_lossless(&mut buffer);
+            img.write_with_encoder(encoder)
+                .map_err(|e| JsValue::from_str(&format!("Failed to encode WebP: {}", e)))?;
+            Ok((buffer, "webp"))
+        }
+    }
+}
+
 #[wasm_bindgen]
-pub fn composite_images(base_image_data: &[u8], overlay_image_data: &[u8]) -> Result<String, JsValue> {
+pub fn composite_images(
+    base_image_data: &[u8],
+    overlay_image_data: &[u8],
+    format: &str,
+    quality: u8,
+    background_color: &[u8],
+) -> Result<String, JsValue> {
     // Decode base image
     let base_img = image::load_from_memory(base_image_data)
         .map_err(|e| JsValue::from_str(&format!("Failed to decode base image: {}", e)))?;
-    
+
     // Decode overlay image
     let overlay_img = image::load_from_memory(overlay_image_data)
         .map_err(|e| JsValue::from_str(&format!("Failed to decode overlay image: {}", e)))?;
-    
+
     // Create a new image buffer for the result
     let mut result_img = base_img.to_rgba8();
-    
+
     // Composite the images (overlay on top of base)
     for (x, y, overlay_pixel) in overlay_img.to_rgba8().enumerate_pixels() {
         if x < result_img.width() && y < result_img.height() {
@@ -23,30 +108,35 @@ pub fn composite_images(base_image_data: &[u8], overlay_image_data: &[u8]) -> Re
             result_img.put_pixel(x, y, composited_pixel);
         }
     }
-    
-    // Encode result to PNG
-    let mut buffer = Vec::new();
-    let result_buffer = image::ImageBuffer::from(result_img);
-    result_buffer.write_to(&mut std::io::Cursor::new(&mut buffer), ImageFormat::Png)
-        .map_err(|e| JsValue::from_str(&format!("Failed to encode result image: {}", e)))?;
-    
+
+    // Encode result in the requested format
+    let output_format = parse_output_format(format, quality)?;
+    let background = parse_background(background_color);
+    let (buffer, mime) = encode_output(&result_img, output_format, background)?;
+
     // Convert to base64 for transfer
     let base64_data = general_purpose::STANDARD.encode(&buffer);
-    Ok(format!("data:image/png;base64,{}", base64_data))
+    Ok(format!("data:image/{};base64,{}", mime, base64_data))
 }
 
 #[wasm_bindgen]
-pub fn composite_multiple_layers(base_image_data: &[u8], layers_data: js_sys::Array) -> Result<String, JsValue> {
+pub fn composite_multiple_layers(
+    base_image_data: &[u8],
+    layers_data: js_sys::Array,
+    format: &str,
+    quality: u8,
+    background_color: &[u8],
+) -> Result<String, JsValue> {
     // Decode base image
     let mut result_img = image::load_from_memory(base_image_data)
         .map_err(|e| JsValue::from_str(&format!("Failed to decode base image: {}", e)))?
         .to_rgba8();
-    
+
     // Process each overlay layer
     for layer_value in layers_data.iter() {
         let layer_base64 = layer_value.as_string()
             .ok_or_else(|| JsValue::from_str("Layer data must be a string"))?;
-        
+
         // Remove data URL prefix if present
         let base64_content = if layer_base64.starts_with("data:image") {
             layer_base64.split(",").nth(1)
@@ -54,17 +144,17 @@ pub fn composite_multiple_layers(base_image_data: &[u8], layers_data: js_sys::Ar
         } else {
             &layer_base64
         };
-        
+
         // Decode base64 to bytes
         let overlay_bytes = general_purpose::STANDARD
             .decode(base64_content)
             .map_err(|e| JsValue::from_str(&format!("Failed to decode base64: {}", e)))?;
-        
+
         // Load overlay image
         let overlay_img = image::load_from_memory(&overlay_bytes)
             .map_err(|e| JsValue::from_str(&format!("Failed to decode overlay image: {}", e)))?
             .to_rgba8();
-        
+
         // Composite the images
         for (x, y, overlay_pixel) in overlay_img.enumerate_pixels() {
             if x < result_img.width() && y < result_img.height() {
@@ -74,42 +164,281 @@ pub fn composite_multiple_layers(base_image_data: &[u8], layers_data: js_sys::Ar
             }
         }
     }
-    
-    // Encode result to PNG
+
+    // Encode result in the requested format
+    let output_format = parse_output_format(format, quality)?;
+    let background = parse_background(background_color);
+    let (buffer, mime) = encode_output(&result_img, output_format, background)?;
+
+    // Convert to base64 for transfer
+    let base64_data = general_purpose::STANDARD.encode(&buffer);
+    Ok(format!("data:image/{};base64,{}", mime, base64_data))
+}
+
+// Extracts an embedded ICC profile from an encoded image. PNG, JPEG and WebP inputs are
+// inspected; any other format yields no profile.
+// Absence or a decode error both just mean "no profile" — callers treat that as the
+// existing sRGB assumption, not a hard failure.
+fn extract_icc_profile(bytes: &[u8]) -> Option<Vec<u8>> {
+    let format = image::guess_format(bytes).ok()?;
+    let mut reader = std::io::Cursor::new(bytes);
+    let profile = match format {
+        ImageFormat::Png => image::codecs::png::PngDecoder::new(&mut reader).ok()?.icc_profile().ok()?,
+        ImageFormat::Jpeg => image::codecs::jpeg::JpegDecoder::new(&mut reader).ok()?.icc_profile().ok()?,
+        ImageFormat::WebP => image::codecs::webp::WebPDecoder::new(&mut reader).ok()?.icc_profile().ok()?,
+        _ => None,
+    };
+    profile.filter(|p| !p.is_empty())
+}
+
+// Transforms `img` in place between two ICC profiles using an lcms2 transform.
+fn apply_icc_transform(
+    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    input_profile: &lcms2::Profile,
+    output_profile: &lcms2::Profile,
+) -> Result<(), JsValue> {
+    let transform = lcms2::Transform::new(
+        input_profile,
+        lcms2::PixelFormat::RGBA_8,
+        output_profile,
+        lcms2::PixelFormat::RGBA_8,
+        lcms2::Intent::RelativeColorimetric,
+    )
+    .map_err(|e| JsValue::from_str(&format!("Failed to build ICC transform: {}", e)))?;
+
+    // `[u8]` slices are a special case in lcms2's `transform_in_place`: it divides the
+    // slice length by the pixel format's bytes-per-pixel (4 for RGBA_8) to get the
+    // pixel count, so the flattened sub-pixel buffer is exactly what it expects here.
+    transform.transform_in_place(img.as_mut());
+    Ok(())
+}
+
+// Converts a decoded layer from its embedded ICC profile into the sRGB working space
+// that `blend_pixels` assumes.
+fn icc_transform_to_working_space(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, profile_bytes: &[u8]) -> Result<(), JsValue> {
+    let input_profile = lcms2::Profile::new_icc(profile_bytes)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse ICC profile: {}", e)))?;
+    let working_profile = lcms2::Profile::new_srgb();
+    apply_icc_transform(img, &input_profile, &working_profile)
+}
+
+// Converts the composited sRGB working-space result into the requested output profile.
+fn icc_transform_from_working_space(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, profile_bytes: &[u8]) -> Result<(), JsValue> {
+    let working_profile = lcms2::Profile::new_srgb();
+    let output_profile = lcms2::Profile::new_icc(profile_bytes)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse ICC profile: {}", e)))?;
+    apply_icc_transform(img, &working_profile, &output_profile)
+}
+
+// Encodes an RGBA image as PNG, embedding an ICC profile chunk when one is given.
+// `image::codecs::png::PngEncoder` has no ICC hook, so an ICC profile goes through
+// the `png` crate directly instead; with no profile this delegates to the same
+// `image`-crate PNG path every other entry point uses, so the no-profile output is
+// byte-identical to theirs rather than merely pixel-identical.
+fn encode_png_with_icc(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, icc_profile: Option<&[u8]>) -> Result<Vec<u8>, JsValue> {
     let mut buffer = Vec::new();
-    let result_buffer = image::ImageBuffer::from(result_img);
-    result_buffer.write_to(&mut std::io::Cursor::new(&mut buffer), ImageFormat::Png)
-        .map_err(|e| JsValue::from_str(&format!("Failed to encode result image: {}", e)))?;
-    
+
+    let Some(profile) = icc_profile else {
+        img.write_to(&mut std::io::Cursor::new(&mut buffer), ImageFormat::Png)
+            .map_err(|e| JsValue::from_str(&format!("Failed to encode PNG: {}", e)))?;
+        return Ok(buffer);
+    };
+
+    // The ICC profile is a field on `png::Info`, not a setter on `Encoder` — build the
+    // info up front and hand it to `Encoder::with_info`.
+    let mut info = png::Info::with_size(img.width(), img.height());
+    info.color_type = png::ColorType::Rgba;
+    info.bit_depth = png::BitDepth::Eight;
+    info.icc_profile = Some(std::borrow::Cow::Owned(profile.to_vec()));
+    let encoder = png::Encoder::with_info(&mut buffer, info)
+        .map_err(|e| JsValue::from_str(&format!("Failed to configure PNG encoder: {}", e)))?;
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| JsValue::from_str(&format!("Failed to write PNG header: {}", e)))?;
+    writer
+        .write_image_data(img.as_raw())
+        .map_err(|e| JsValue::from_str(&format!("Failed to write PNG data: {}", e)))?;
+    drop(writer);
+    Ok(buffer)
+}
+
+/// Color-managed variant of `composite_multiple_layers`: when a decoded layer carries
+/// an embedded ICC profile, its pixels are transformed into a common sRGB working
+/// space before `blend_pixels` runs, and the composited result is converted to
+/// `target_profile` (if given) and embedded in the output PNG. With no embedded
+/// profiles anywhere and no `target_profile`, this produces byte-identical output to
+/// `composite_multiple_layers` called with the `"png"` format.
+#[wasm_bindgen]
+pub fn composite_multiple_layers_managed(
+    base_image_data: &[u8],
+    layers_data: js_sys::Array,
+    target_profile: Option<Vec<u8>>,
+) -> Result<String, JsValue> {
+    // Decode base image
+    let mut result_img = image::load_from_memory(base_image_data)
+        .map_err(|e| JsValue::from_str(&format!("Failed to decode base image: {}", e)))?
+        .to_rgba8();
+    if let Some(profile) = extract_icc_profile(base_image_data) {
+        icc_transform_to_working_space(&mut result_img, &profile)?;
+    }
+
+    // Process each overlay layer
+    for layer_value in layers_data.iter() {
+        let layer_base64 = layer_value.as_string()
+            .ok_or_else(|| JsValue::from_str("Layer data must be a string"))?;
+
+        // Remove data URL prefix if present
+        let base64_content = if layer_base64.starts_with("data:image") {
+            layer_base64.split(",").nth(1)
+                .ok_or_else(|| JsValue::from_str("Invalid data URL format"))?
+        } else {
+            &layer_base64
+        };
+
+        // Decode base64 to bytes
+        let overlay_bytes = general_purpose::STANDARD
+            .decode(base64_content)
+            .map_err(|e| JsValue::from_str(&format!("Failed to decode base64: {}", e)))?;
+
+        // Load overlay image
+        let mut overlay_img = image::load_from_memory(&overlay_bytes)
+            .map_err(|e| JsValue::from_str(&format!("Failed to decode overlay image: {}", e)))?
+            .to_rgba8();
+        if let Some(profile) = extract_icc_profile(&overlay_bytes) {
+            icc_transform_to_working_space(&mut overlay_img, &profile)?;
+        }
+
+        // Composite the images
+        for (x, y, overlay_pixel) in overlay_img.enumerate_pixels() {
+            if x < result_img.width() && y < result_img.height() {
+                let base_pixel = result_img.get_pixel(x, y);
+                let composited_pixel = blend_pixels(*base_pixel, *overlay_pixel);
+                result_img.put_pixel(x, y, composited_pixel);
+            }
+        }
+    }
+
+    if let Some(ref target) = target_profile {
+        icc_transform_from_working_space(&mut result_img, target)?;
+    }
+
+    // Encode result to PNG, embedding the output profile if one was requested
+    let buffer = encode_png_with_icc(&result_img, target_profile.as_deref())?;
+
     // Convert to base64 for transfer
     let base64_data = general_purpose::STANDARD.encode(&buffer);
     Ok(format!("data:image/png;base64,{}", base64_data))
 }
 
+/// Composites layers with per-layer placement (position, scaling, opacity and blend
+/// mode), unlike `composite_multiple_layers` which always pastes at (0,0) with
+/// straight alpha-over. Each entry in `layers_data` is a JS object:
+/// `{ image, dx?, dy?, width?, height?, opacity?, blendMode? }`, where `image` is a
+/// base64 string or data URL and the rest are optional placement overrides. The layer
+/// is resized only when both `width` and `height` are given; one alone is ignored.
+#[wasm_bindgen]
+pub fn composite_layers_advanced(
+    base_image_data: &[u8],
+    layers_data: js_sys::Array,
+    format: &str,
+    quality: u8,
+    background_color: &[u8],
+) -> Result<String, JsValue> {
+    // Decode base image
+    let mut result_img = image::load_from_memory(base_image_data)
+        .map_err(|e| JsValue::from_str(&format!("Failed to decode base image: {}", e)))?
+        .to_rgba8();
+
+    // Process each overlay layer
+    for layer_value in layers_data.iter() {
+        let layer_obj: js_sys::Object = layer_value
+            .dyn_into()
+            .map_err(|_| JsValue::from_str("Layer entry must be an object"))?;
+
+        let image_field = js_sys::Reflect::get(&layer_obj, &JsValue::from_str("image"))?;
+        let layer_base64 = image_field
+            .as_string()
+            .ok_or_else(|| JsValue::from_str("Layer 'image' field must be a string"))?;
+
+        // Remove data URL prefix if present
+        let base64_content = if layer_base64.starts_with("data:image") {
+            layer_base64.split(",").nth(1)
+                .ok_or_else(|| JsValue::from_str("Invalid data URL format"))?
+        } else {
+            &layer_base64
+        };
+
+        // Decode base64 to bytes
+        let overlay_bytes = general_purpose::STANDARD
+            .decode(base64_content)
+            .map_err(|e| JsValue::from_str(&format!("Failed to decode base64: {}", e)))?;
+
+        // Load overlay image
+        let mut overlay_img = image::load_from_memory(&overlay_bytes)
+            .map_err(|e| JsValue::from_str(&format!("Failed to decode overlay image: {}", e)))?
+            .to_rgba8();
+
+        let target_width = reflect_get_f64(&layer_obj, "width")?.map(|v| v as u32);
+        let target_height = reflect_get_f64(&layer_obj, "height")?.map(|v| v as u32);
+        if let (Some(w), Some(h)) = (target_width, target_height) {
+            if w != overlay_img.width() || h != overlay_img.height() {
+                overlay_img = image::imageops::resize(&overlay_img, w, h, image::imageops::Lanczos3);
+            }
+        }
+
+        let dx = reflect_get_f64(&layer_obj, "dx")?.unwrap_or(0.0) as i32;
+        let dy = reflect_get_f64(&layer_obj, "dy")?.unwrap_or(0.0) as i32;
+        let opacity = reflect_get_f64(&layer_obj, "opacity")?.unwrap_or(1.0) as f32;
+        let blend_mode = match reflect_get_string(&layer_obj, "blendMode")? {
+            Some(name) => BlendMode::from_name(&name)?,
+            None => BlendMode::Normal,
+        };
+
+        blit_layer(&mut result_img, &overlay_img, dx, dy, opacity, blend_mode);
+    }
+
+    // Encode result in the requested format
+    let output_format = parse_output_format(format, quality)?;
+    let background = parse_background(background_color);
+    let (buffer, mime) = encode_output(&result_img, output_format, background)?;
+
+    // Convert to base64 for transfer
+    let base64_data = general_purpose::STANDARD.encode(&buffer);
+    Ok(format!("data:image/{};base64,{}", mime, base64_data))
+}
+
 #[wasm_bindgen]
-pub fn generate_preview(base_image_data: &[u8], overlay_image_data: &[u8], width: u32, height: u32) -> Result<String, JsValue> {
+pub fn generate_preview(
+    base_image_data: &[u8],
+    overlay_image_data: &[u8],
+    width: u32,
+    height: u32,
+    format: &str,
+    quality: u8,
+    background_color: &[u8],
+) -> Result<String, JsValue> {
     // Decode base image
     let mut base_img = image::load_from_memory(base_image_data)
         .map_err(|e| JsValue::from_str(&format!("Failed to decode base image: {}", e)))?
         .to_rgba8();
-    
+
     // Resize base image to preview size
     if base_img.width() != width || base_img.height() != height {
         base_img = image::imageops::resize(&base_img, width, height, image::imageops::Lanczos3);
     }
-    
+
     // Decode overlay image
     let overlay_img = image::load_from_memory(overlay_image_data)
         .map_err(|e| JsValue::from_str(&format!("Failed to decode overlay image: {}", e)))?
         .to_rgba8();
-    
+
     // Resize overlay to match preview size
     let resized_overlay = if overlay_img.width() != width || overlay_img.height() != height {
         image::imageops::resize(&overlay_img, width, height, image::imageops::Lanczos3)
     } else {
         overlay_img
     };
-    
+
     // Composite the images
     for (x, y, overlay_pixel) in resized_overlay.enumerate_pixels() {
         if x < base_img.width() && y < base_img.height() {
@@ -118,16 +447,99 @@ pub fn generate_preview(base_image_data: &[u8], overlay_image_data: &[u8], width
             base_img.put_pixel(x, y, composited_pixel);
         }
     }
-    
-    // Encode result to PNG
-    let mut buffer = Vec::new();
-    let result_buffer = image::ImageBuffer::from(base_img);
-    result_buffer.write_to(&mut std::io::Cursor::new(&mut buffer), ImageFormat::Png)
-        .map_err(|e| JsValue::from_str(&format!("Failed to encode result image: {}", e)))?;
-    
+
+    // Encode result in the requested format
+    let output_format = parse_output_format(format, quality)?;
+    let background = parse_background(background_color);
+    let (buffer, mime) = encode_output(&base_img, output_format, background)?;
+
     // Convert to base64 for transfer
     let base64_data = general_purpose::STANDARD.encode(&buffer);
-    Ok(format!("data:image/png;base64,{}", base64_data))
+    Ok(format!("data:image/{};base64,{}", mime, base64_data))
+}
+
+/// Builds an animated GIF from a static base image plus a sequence of overlay
+/// frame-sets, so collections can ship animated accessories (glowing eyes, flickering
+/// effects) without baking every frame into JavaScript. `frame_layers` is an array of
+/// arrays of base64 (or data URL) layer strings, one inner array per frame, composited
+/// over a clone of the base the same way `composite_multiple_layers` does.
+/// `delays_ms` holds one delay per frame; `loop_count` of 0 means loop forever.
+#[wasm_bindgen]
+pub fn composite_animation(
+    base_image_data: &[u8],
+    frame_layers: js_sys::Array,
+    delays_ms: js_sys::Array,
+    loop_count: u32,
+) -> Result<String, JsValue> {
+    let base_img = image::load_from_memory(base_image_data)
+        .map_err(|e| JsValue::from_str(&format!("Failed to decode base image: {}", e)))?
+        .to_rgba8();
+
+    if frame_layers.length() != delays_ms.length() {
+        return Err(JsValue::from_str("frame_layers and delays_ms must have the same length"));
+    }
+
+    let mut buffer = Vec::new();
+    {
+        let mut encoder = image::codecs::gif::GifEncoder::new(&mut buffer);
+        encoder
+            .set_repeat(if loop_count == 0 {
+                image::codecs::gif::Repeat::Infinite
+            } else {
+                image::codecs::gif::Repeat::Finite(loop_count as u16)
+            })
+            .map_err(|e| JsValue::from_str(&format!("Failed to set loop count: {}", e)))?;
+
+        for (layers_value, delay_value) in frame_layers.iter().zip(delays_ms.iter()) {
+            let layers: js_sys::Array = layers_value
+                .dyn_into()
+                .map_err(|_| JsValue::from_str("Each frame entry must be an array of layer strings"))?;
+
+            // Composite this frame's layers over a clone of the base, the same way
+            // composite_multiple_layers does.
+            let mut frame_img = base_img.clone();
+            for layer_value in layers.iter() {
+                let layer_base64 = layer_value.as_string()
+                    .ok_or_else(|| JsValue::from_str("Layer data must be a string"))?;
+
+                let base64_content = if layer_base64.starts_with("data:image") {
+                    layer_base64.split(",").nth(1)
+                        .ok_or_else(|| JsValue::from_str("Invalid data URL format"))?
+                } else {
+                    &layer_base64
+                };
+
+                let overlay_bytes = general_purpose::STANDARD
+                    .decode(base64_content)
+                    .map_err(|e| JsValue::from_str(&format!("Failed to decode base64: {}", e)))?;
+
+                let overlay_img = image::load_from_memory(&overlay_bytes)
+                    .map_err(|e| JsValue::from_str(&format!("Failed to decode overlay image: {}", e)))?
+                    .to_rgba8();
+
+                for (x, y, overlay_pixel) in overlay_img.enumerate_pixels() {
+                    if x < frame_img.width() && y < frame_img.height() {
+                        let base_pixel = frame_img.get_pixel(x, y);
+                        let composited_pixel = blend_pixels(*base_pixel, *overlay_pixel);
+                        frame_img.put_pixel(x, y, composited_pixel);
+                    }
+                }
+            }
+
+            let delay_ms = delay_value
+                .as_f64()
+                .ok_or_else(|| JsValue::from_str("Frame delay must be a number"))?;
+            let delay = image::Delay::from_saturating_duration(std::time::Duration::from_millis(delay_ms as u64));
+            let frame = image::Frame::from_parts(frame_img, 0, 0, delay);
+
+            encoder
+                .encode_frame(frame)
+                .map_err(|e| JsValue::from_str(&format!("Failed to encode frame: {}", e)))?;
+        }
+    }
+
+    let base64_data = general_purpose::STANDARD.encode(&buffer);
+    Ok(format!("data:image/gif;base64,{}", base64_data))
 }
 
 // Helper function to blend pixels with alpha
@@ -148,8 +560,295 @@ fn blend_pixels(base: Rgba<u8>, overlay: Rgba<u8>) -> Rgba<u8> {
     Rgba([r, g, b, a])
 }
 
+/// Blend mode used when placing a positioned layer onto the canvas in
+/// `composite_layers_advanced`. Each variant blends the color channels before the
+/// usual alpha-over composite is applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    Add,
+}
+
+impl BlendMode {
+    fn from_name(name: &str) -> Result<Self, JsValue> {
+        match name {
+            "normal" => Ok(BlendMode::Normal),
+            "multiply" => Ok(BlendMode::Multiply),
+            "screen" => Ok(BlendMode::Screen),
+            "overlay" => Ok(BlendMode::Overlay),
+            "darken" => Ok(BlendMode::Darken),
+            "lighten" => Ok(BlendMode::Lighten),
+            "add" => Ok(BlendMode::Add),
+            other => Err(JsValue::from_str(&format!("Unknown blend mode: {}", other))),
+        }
+    }
+
+    fn blend_channel(self, base: f32, overlay: f32) -> f32 {
+        match self {
+            BlendMode::Normal => overlay,
+            BlendMode::Multiply => base * overlay,
+            BlendMode::Screen => 1.0 - (1.0 - base) * (1.0 - overlay),
+            BlendMode::Overlay => {
+                if base <= 0.5 {
+                    2.0 * base * overlay
+                } else {
+                    1.0 - 2.0 * (1.0 - base) * (1.0 - overlay)
+                }
+            }
+            BlendMode::Darken => base.min(overlay),
+            BlendMode::Lighten => base.max(overlay),
+            BlendMode::Add => (base + overlay).min(1.0),
+        }
+    }
+}
+
+// Helper function to blend pixels with a chosen blend mode and opacity multiplier,
+// generalizing `blend_pixels` (which is equivalent to `BlendMode::Normal` at full opacity).
+fn blend_pixels_mode(base: Rgba<u8>, overlay: Rgba<u8>, mode: BlendMode, opacity: f32) -> Rgba<u8> {
+    let base_alpha = base[3] as f32 / 255.0;
+    let overlay_alpha = (overlay[3] as f32 / 255.0) * opacity.clamp(0.0, 1.0);
+    let result_alpha = overlay_alpha + base_alpha * (1.0 - overlay_alpha);
+
+    if result_alpha == 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    let mix_channel = |channel: usize| -> u8 {
+        let base_c = base[channel] as f32 / 255.0;
+        let overlay_c = overlay[channel] as f32 / 255.0;
+        let blended_c = mode.blend_channel(base_c, overlay_c) * 255.0;
+        let base_raw = base[channel] as f32;
+        ((blended_c * overlay_alpha + base_raw * base_alpha * (1.0 - overlay_alpha)) / result_alpha) as u8
+    };
+
+    Rgba([mix_channel(0), mix_channel(1), mix_channel(2), (result_alpha * 255.0) as u8])
+}
+
+// Blits `overlay` onto `canvas` at offset (dx, dy), clipping the source rect against
+// the destination bounds so negative offsets and partially off-canvas layers work
+// correctly (fully out-of-bounds layers are skipped entirely).
+fn blit_layer(
+    canvas: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    overlay: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    dx: i32,
+    dy: i32,
+    opacity: f32,
+    mode: BlendMode,
+) {
+    let canvas_width = canvas.width() as i32;
+    let canvas_height = canvas.height() as i32;
+    let overlay_width = overlay.width() as i32;
+    let overlay_height = overlay.height() as i32;
+
+    let src_x_start = (-dx).max(0);
+    let src_y_start = (-dy).max(0);
+    let src_x_end = (canvas_width - dx).min(overlay_width);
+    let src_y_end = (canvas_height - dy).min(overlay_height);
+
+    if src_x_start >= src_x_end || src_y_start >= src_y_end {
+        return;
+    }
+
+    for sy in src_y_start..src_y_end {
+        for sx in src_x_start..src_x_end {
+            let cx = (sx + dx) as u32;
+            let cy = (sy + dy) as u32;
+            let base_pixel = *canvas.get_pixel(cx, cy);
+            let overlay_pixel = *overlay.get_pixel(sx as u32, sy as u32);
+            canvas.put_pixel(cx, cy, blend_pixels_mode(base_pixel, overlay_pixel, mode, opacity));
+        }
+    }
+}
+
+// Reads an optional numeric field from a JS object, erroring if present but non-numeric.
+fn reflect_get_f64(obj: &js_sys::Object, key: &str) -> Result<Option<f64>, JsValue> {
+    let value = js_sys::Reflect::get(obj, &JsValue::from_str(key))?;
+    if value.is_undefined() || value.is_null() {
+        Ok(None)
+    } else {
+        value
+            .as_f64()
+            .map(Some)
+            .ok_or_else(|| JsValue::from_str(&format!("Field '{}' must be a number", key)))
+    }
+}
+
+// Reads an optional string field from a JS object.
+fn reflect_get_string(obj: &js_sys::Object, key: &str) -> Result<Option<String>, JsValue> {
+    let value = js_sys::Reflect::get(obj, &JsValue::from_str(key))?;
+    Ok(value.as_string())
+}
+
 // Additional helper functions for WASM integration
 #[wasm_bindgen]
 pub fn init_console_panic_hook() {
     console_error_panic_hook::set_once();
+}
+
+// BlurHash base83 alphabet, as specified by the reference implementation.
+const BASE83_ALPHABET: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// Generates a BlurHash placeholder string for composited NFT art, so front-ends can
+/// show a tiny blurred preview while the full image streams in.
+#[wasm_bindgen]
+pub fn blurhash_encode(image_data: &[u8], components_x: u32, components_y: u32) -> Result<String, JsValue> {
+    let components_x = components_x.clamp(1, 9);
+    let components_y = components_y.clamp(1, 9);
+
+    let img = image::load_from_memory(image_data)
+        .map_err(|e| JsValue::from_str(&format!("Failed to decode image: {}", e)))?
+        .to_rgba8();
+    let (width, height) = (img.width(), img.height());
+
+    // Decode to linear light so the DCT-style basis functions average in linear space.
+    let mut linear = vec![[0f32; 3]; (width * height) as usize];
+    for (x, y, pixel) in img.enumerate_pixels() {
+        let idx = (y * width + x) as usize;
+        linear[idx] = [
+            srgb_to_linear(pixel[0]),
+            srgb_to_linear(pixel[1]),
+            srgb_to_linear(pixel[2]),
+        ];
+    }
+
+    let mut factors = Vec::with_capacity((components_x * components_y) as usize);
+    for j in 0..components_y {
+        for i in 0..components_x {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0f32; 3];
+            for y in 0..height {
+                let basis_y = (std::f32::consts::PI * j as f32 * y as f32 / height as f32).cos();
+                for x in 0..width {
+                    let basis = (std::f32::consts::PI * i as f32 * x as f32 / width as f32).cos() * basis_y;
+                    let px = linear[(y * width + x) as usize];
+                    factor[0] += basis * px[0];
+                    factor[1] += basis * px[1];
+                    factor[2] += basis * px[2];
+                }
+            }
+            let scale = normalisation / (width as f32 * height as f32);
+            factors.push([factor[0] * scale, factor[1] * scale, factor[2] * scale]);
+        }
+    }
+
+    let mut hash = String::new();
+
+    // Size flag: encodes the component counts.
+    let size_flag = (components_x - 1) + (components_y - 1) * 9;
+    hash.push_str(&base83_encode(size_flag, 1));
+
+    let dc = factors[0];
+    let ac = &factors[1..];
+
+    let maximum_value = if !ac.is_empty() {
+        let actual_max = ac_max_abs(ac);
+        let quantised_max = ((actual_max * 166.0 - 0.5).floor() as i32).clamp(0, 82) as u32;
+        hash.push_str(&base83_encode(quantised_max, 1));
+        (quantised_max as f32 + 1.0) / 166.0
+    } else {
+        hash.push_str(&base83_encode(0, 1));
+        1.0
+    };
+
+    hash.push_str(&base83_encode(encode_dc(dc), 4));
+
+    for factor in ac {
+        hash.push_str(&base83_encode(encode_ac(*factor, maximum_value), 2));
+    }
+
+    Ok(hash)
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let c = value.clamp(0.0, 1.0);
+    let srgb = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0 + 0.5).clamp(0.0, 255.0) as u8
+}
+
+// Largest magnitude among all AC factor channels, used to derive `maximumValue`. AC
+// factors are routinely negative (the basis functions are zero-mean off the DC term),
+// so this must compare by absolute value, not signed value.
+fn ac_max_abs(ac: &[[f32; 3]]) -> f32 {
+    ac.iter()
+        .flat_map(|f| f.iter().copied())
+        .fold(0f32, |acc, v| acc.max(v.abs()))
+}
+
+fn encode_dc(dc: [f32; 3]) -> u32 {
+    let r = linear_to_srgb(dc[0]) as u32;
+    let g = linear_to_srgb(dc[1]) as u32;
+    let b = linear_to_srgb(dc[2]) as u32;
+    (r << 16) + (g << 8) + b
+}
+
+fn encode_ac(factor: [f32; 3], maximum_value: f32) -> u32 {
+    let quantise = |v: f32| -> u32 {
+        (sign_pow(v / maximum_value, 0.5) * 9.0 + 9.5)
+            .floor()
+            .clamp(0.0, 18.0) as u32
+    };
+    let r = quantise(factor[0]);
+    let g = quantise(factor[1]);
+    let b = quantise(factor[2]);
+    r * 19 * 19 + g * 19 + b
+}
+
+fn sign_pow(value: f32, exponent: f32) -> f32 {
+    value.abs().powf(exponent).copysign(value)
+}
+
+fn base83_encode(value: u32, length: usize) -> String {
+    let mut result = vec![0u8; length];
+    let mut value = value;
+    for i in (0..length).rev() {
+        let digit = value % 83;
+        result[i] = BASE83_ALPHABET[digit as usize];
+        value /= 83;
+    }
+    String::from_utf8(result).expect("base83 alphabet is ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ac_max_abs_uses_absolute_value() {
+        let ac = [[-0.9, -0.85, -0.88], [0.02, 0.01, 0.03], [-0.4, -0.3, -0.2]];
+        assert!((ac_max_abs(&ac) - 0.9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn base83_encode_matches_known_values() {
+        assert_eq!(base83_encode(0, 1), "0");
+        assert_eq!(base83_encode(82, 1), "~");
+        assert_eq!(base83_encode(83, 2), "10");
+    }
+
+    #[test]
+    fn srgb_linear_roundtrip_is_close() {
+        for v in [0u8, 1, 64, 128, 200, 255] {
+            let roundtripped = linear_to_srgb(srgb_to_linear(v));
+            assert!((roundtripped as i16 - v as i16).abs() <= 1);
+        }
+    }
 }
\ No newline at end of file